@@ -0,0 +1,457 @@
+use crate::{Error, Key};
+use std::mem::ManuallyDrop;
+
+// Vacant slots are linked into runs: a maximal contiguous stretch of vacant
+// slots. Only the two boundary slots of a run carry meaningful metadata.
+// The first slot of a run stores `prev`/`next`, the indexes of the first
+// slots of the neighboring runs in the free list (u32::MAX for none), plus
+// `other_end`, the index of the run's last slot. The last slot of a run
+// only needs its `other_end` kept up to date, pointing back at the first
+// slot; its `prev`/`next` are never read. This lets both `add` (pop from
+// the front of a run) and `remove` (merge with an adjacent run) touch only
+// the run's boundary slots, and lets iteration jump over an entire run by
+// reading `other_end` once.
+#[derive(Clone, Copy)]
+struct FreeListEntry {
+    prev: u32,
+    next: u32,
+    other_end: u32,
+}
+
+union SlotData<V> {
+    value: ManuallyDrop<V>,
+    free: FreeListEntry,
+}
+struct Slot<V> {
+    version: u32, // even = vacant, odd = occupied
+    data: SlotData<V>,
+}
+impl<V> Slot<V> {
+    fn new(value: V) -> Self {
+        Self {
+            version: 1,
+            data: SlotData { value: ManuallyDrop::new(value) },
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.version % 2 == 0
+    }
+    fn occupy(&mut self, value: V) -> Result<u32, Error> {
+        let version = self.version.checked_add(1).ok_or(Error::MaxVersionReached)?;
+        self.version = version;
+        self.data = SlotData { value: ManuallyDrop::new(value) };
+        Ok(version)
+    }
+    // marks the slot vacant, storing its free-list entry, and returns the
+    // value that was in it
+    unsafe fn vacate(&mut self, entry: FreeListEntry) -> V {
+        self.version = self.version.wrapping_add(1);
+        let value = unsafe { ManuallyDrop::take(&mut self.data.value) };
+        self.data.free = entry;
+        value
+    }
+}
+
+/// A `SlotMap` variant whose free list is organized as a doubly-linked list
+/// of vacant *runs* rather than individual slots, so that iteration can hop
+/// over a whole run of holes in O(1) instead of visiting every vacant slot.
+/// This makes `iter`/`iter_mut` proportional to the number of occupied
+/// slots regardless of how sparse the map is, at the cost of a little extra
+/// bookkeeping in `add` and `remove` (which may need to merge or split a
+/// run instead of a plain push/pop). Prefer [`crate::SlotMap`] unless
+/// iteration over a sparsely-populated map is on a hot path.
+pub struct HopSlotMap<V> {
+    max_slots: usize,
+    unique_counter: u32,
+    data: Vec<Slot<V>>,
+    free_head: u32, // index of the first slot of the first vacant run, or u32::MAX
+    len: usize,
+}
+impl<V> HopSlotMap<V> {
+    pub fn new(initial_slots: u32, max_slots: u32) -> Result<Self, Error> {
+        if initial_slots > max_slots { return Err(Error::InvalidArgument) }
+        if max_slots == u32::MAX { return Err(Error::InvalidArgument) }
+        Ok(Self {
+            max_slots: max_slots as usize,
+            unique_counter: 0,
+            data: Vec::with_capacity(initial_slots as usize),
+            free_head: u32::MAX,
+            len: 0,
+        })
+    }
+    /// returns number of occupied slots
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// returns true if there are no occupied slots
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// returns a key that increments a counter private to this method,
+    /// guaranteeing uniqueness. If used with .get(), it will always result
+    /// in a None value returned.
+    pub fn get_unique_key(&mut self) -> Result<Key<V>, Error> {
+        let counter = self.unique_counter.checked_add(1).ok_or(Error::MaxVersionReached)?;
+        self.unique_counter = counter;
+        Key::new_special(counter)
+    }
+    /// Returns a reference for the value at the given key. This is an O(1)
+    /// operation.
+    pub fn get(&self, key: Key<V>) -> Option<&V> {
+        let index = key.index();
+        self.data.get(index).and_then(|slot| {
+            if !slot.is_empty() && key.version() == slot.version {
+                Some(unsafe { &*slot.data.value })
+            } else {
+                None
+            }
+        })
+    }
+    /// returns a mutable reference for the value at the given key. This is
+    /// an O(1) operation.
+    pub fn get_mut(&mut self, key: Key<V>) -> Option<&mut V> {
+        let index = key.index();
+        self.data.get_mut(index).and_then(|slot| {
+            if !slot.is_empty() && key.version() == slot.version {
+                Some(unsafe { &mut *slot.data.value })
+            } else {
+                None
+            }
+        })
+    }
+    /// adds a new value, returns the key.
+    pub fn add(&mut self, value: V) -> Result<Key<V>, Error> {
+        if self.free_head != u32::MAX {
+            let index = self.free_head as usize;
+            let entry = unsafe { self.data[index].data.free };
+            if entry.other_end as usize == index {
+                // this run is a single slot: drop it from the free list
+                self.free_head = entry.next;
+                if entry.next != u32::MAX {
+                    self.data[entry.next as usize].data.free.prev = u32::MAX;
+                }
+            } else {
+                // shrink the run from the front, moving its list position
+                // and other_end to the new first slot
+                let new_first = index + 1;
+                let last = entry.other_end as usize;
+                self.data[new_first].data.free = FreeListEntry {
+                    prev: entry.prev,
+                    next: entry.next,
+                    other_end: entry.other_end,
+                };
+                self.data[last].data.free.other_end = new_first as u32;
+                if entry.prev != u32::MAX {
+                    self.data[entry.prev as usize].data.free.next = new_first as u32;
+                } else {
+                    self.free_head = new_first as u32;
+                }
+                if entry.next != u32::MAX {
+                    self.data[entry.next as usize].data.free.prev = new_first as u32;
+                }
+            }
+            let version = self.data[index].occupy(value)?;
+            self.len += 1;
+            Key::new(index, version)
+        } else {
+            let index = self.data.len();
+            if index >= self.max_slots {
+                return Err(Error::NoFreeSlots);
+            }
+            self.data.push(Slot::new(value));
+            self.len += 1;
+            Key::new(index, 1)
+        }
+    }
+    /// Returns the value stored in the slot, or None if the key is out of
+    /// date or the slot is empty. Merges the freed slot with any adjacent
+    /// vacant run.
+    pub fn remove(&mut self, key: Key<V>) -> Option<V> {
+        let index = key.index();
+        match self.data.get(index) {
+            Some(slot) if !slot.is_empty() && slot.version == key.version() => {}
+            _ => return None,
+        }
+        if self.data[index].version == u32::MAX {
+            // Bumping the version would wrap it back to an already-issued
+            // one, letting a long-stale Key for this slot start matching
+            // again. Retire the slot instead: it becomes a self-contained,
+            // never-reused run of one so iteration still hops over it
+            // correctly, but it is never linked into the free list (left
+            // and right neighbors, if vacant, are left as separate runs
+            // rather than merged through it).
+            self.len -= 1;
+            let entry = FreeListEntry { prev: u32::MAX, next: u32::MAX, other_end: index as u32 };
+            return Some(unsafe { self.data[index].vacate(entry) });
+        }
+        // a vacant slot next to the one being freed is always a true
+        // boundary of its run, since the slot being freed was occupied. A
+        // retired slot (version wrapped to 0) is vacant but never merged
+        // with, so it stays out of the free list for good.
+        let mergeable = |slot: &Slot<V>| slot.is_empty() && slot.version != 0;
+        let left_vacant = index > 0 && mergeable(&self.data[index - 1]);
+        let right_vacant = index + 1 < self.data.len() && mergeable(&self.data[index + 1]);
+        let entry = match (left_vacant, right_vacant) {
+            (false, false) => {
+                let entry = FreeListEntry { prev: u32::MAX, next: self.free_head, other_end: index as u32 };
+                if self.free_head != u32::MAX {
+                    self.data[self.free_head as usize].data.free.prev = index as u32;
+                }
+                self.free_head = index as u32;
+                entry
+            }
+            (false, true) => {
+                // extend the run starting at index + 1 to start at index instead
+                let right_first = index + 1;
+                let right_entry = unsafe { self.data[right_first].data.free };
+                let right_last = right_entry.other_end as usize;
+                self.data[right_last].data.free.other_end = index as u32;
+                if right_entry.prev != u32::MAX {
+                    self.data[right_entry.prev as usize].data.free.next = index as u32;
+                } else {
+                    self.free_head = index as u32;
+                }
+                if right_entry.next != u32::MAX {
+                    self.data[right_entry.next as usize].data.free.prev = index as u32;
+                }
+                FreeListEntry { prev: right_entry.prev, next: right_entry.next, other_end: right_last as u32 }
+            }
+            (true, false) => {
+                // extend the run ending at index - 1 to end at index instead
+                let left_last = index - 1;
+                let left_first = unsafe { self.data[left_last].data.free.other_end } as usize;
+                self.data[left_first].data.free.other_end = index as u32;
+                FreeListEntry { prev: u32::MAX, next: u32::MAX, other_end: left_first as u32 }
+            }
+            (true, true) => {
+                // merge the left run, this slot, and the right run into one
+                let left_last = index - 1;
+                let left_first = unsafe { self.data[left_last].data.free.other_end } as usize;
+                let right_first = index + 1;
+                let right_entry = unsafe { self.data[right_first].data.free };
+                let right_last = right_entry.other_end as usize;
+                // the right run is absorbed; unlink it from the free list
+                if right_entry.prev != u32::MAX {
+                    self.data[right_entry.prev as usize].data.free.next = right_entry.next;
+                } else {
+                    self.free_head = right_entry.next;
+                }
+                if right_entry.next != u32::MAX {
+                    self.data[right_entry.next as usize].data.free.prev = right_entry.prev;
+                }
+                self.data[left_first].data.free.other_end = right_last as u32;
+                self.data[right_last].data.free.other_end = left_first as u32;
+                FreeListEntry { prev: u32::MAX, next: u32::MAX, other_end: left_first as u32 }
+            }
+        };
+        self.len -= 1;
+        Some(unsafe { self.data[index].vacate(entry) })
+    }
+    /// returns an iterator over `(Key<V>, &V)` pairs for every occupied
+    /// slot. Runs of vacant slots are skipped in O(1) per run rather than
+    /// per slot.
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter { data: &self.data, index: 0 }
+    }
+    /// like [`HopSlotMap::iter`], but yields mutable references to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, V> {
+        IterMut { data: &mut self.data, index: 0 }
+    }
+    /// returns an iterator over the keys of every occupied slot.
+    pub fn keys(&self) -> Keys<'_, V> {
+        Keys { inner: self.iter() }
+    }
+    /// returns an iterator over references to every occupied value.
+    pub fn values(&self) -> Values<'_, V> {
+        Values { inner: self.iter() }
+    }
+    /// returns an iterator over mutable references to every occupied value.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, V> {
+        ValuesMut { inner: self.iter_mut() }
+    }
+}
+impl<V> Drop for HopSlotMap<V> {
+    fn drop(&mut self) {
+        for slot in self.data.iter_mut() {
+            if !slot.is_empty() {
+                unsafe { ManuallyDrop::drop(&mut slot.data.value) };
+            }
+        }
+    }
+}
+
+/// Iterator over `(Key<V>, &V)` pairs, returned by [`HopSlotMap::iter`].
+pub struct Iter<'a, V> {
+    data: &'a [Slot<V>],
+    index: usize,
+}
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (Key<V>, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let slot = self.data.get(self.index)?;
+            if slot.is_empty() {
+                // the cursor is always at the first slot of a run here, so
+                // other_end hops straight past it to the next occupied slot
+                let other_end = unsafe { slot.data.free.other_end };
+                self.index = other_end as usize + 1;
+                continue;
+            }
+            let index = self.index;
+            let key = Key::new(index, slot.version).expect("occupied slot has a valid index");
+            self.index += 1;
+            return Some((key, unsafe { &slot.data.value }));
+        }
+    }
+}
+impl<'a, V> std::iter::FusedIterator for Iter<'a, V> {}
+
+/// Iterator over `(Key<V>, &mut V)` pairs, returned by [`HopSlotMap::iter_mut`].
+pub struct IterMut<'a, V> {
+    data: &'a mut [Slot<V>],
+    index: usize,
+}
+impl<'a, V> Iterator for IterMut<'a, V> {
+    type Item = (Key<V>, &'a mut V);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let data = std::mem::take(&mut self.data);
+            if data.is_empty() {
+                return None;
+            }
+            if data[0].is_empty() {
+                let other_end = unsafe { data[0].data.free.other_end } as usize;
+                let run_len = other_end - self.index + 1;
+                let (_, rest) = data.split_at_mut(run_len);
+                self.index += run_len;
+                self.data = rest;
+                continue;
+            }
+            let (first, rest) = data.split_first_mut().expect("slice is non-empty");
+            let key = Key::new(self.index, first.version).expect("occupied slot has a valid index");
+            self.index += 1;
+            self.data = rest;
+            return Some((key, unsafe { &mut first.data.value }));
+        }
+    }
+}
+impl<'a, V> std::iter::FusedIterator for IterMut<'a, V> {}
+
+/// Iterator over keys, returned by [`HopSlotMap::keys`].
+pub struct Keys<'a, V> {
+    inner: Iter<'a, V>,
+}
+impl<'a, V> Iterator for Keys<'a, V> {
+    type Item = Key<V>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
+impl<'a, V> std::iter::FusedIterator for Keys<'a, V> {}
+
+/// Iterator over value references, returned by [`HopSlotMap::values`].
+pub struct Values<'a, V> {
+    inner: Iter<'a, V>,
+}
+impl<'a, V> Iterator for Values<'a, V> {
+    type Item = &'a V;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+}
+impl<'a, V> std::iter::FusedIterator for Values<'a, V> {}
+
+/// Iterator over mutable value references, returned by [`HopSlotMap::values_mut`].
+pub struct ValuesMut<'a, V> {
+    inner: IterMut<'a, V>,
+}
+impl<'a, V> Iterator for ValuesMut<'a, V> {
+    type Item = &'a mut V;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+}
+impl<'a, V> std::iter::FusedIterator for ValuesMut<'a, V> {}
+
+#[cfg(test)]
+mod checks {
+    use super::*;
+
+    #[test]
+    fn check_add_remove_get() {
+        let mut x: HopSlotMap<i32> = HopSlotMap::new(2, 4).unwrap();
+        let key1 = x.add(3).unwrap();
+        let key2 = x.add(99).unwrap();
+        assert!(matches!(x.get(key1), Some(3)));
+        let removed = x.remove(key1);
+        assert_eq!(Some(3), removed);
+        assert!(x.get(key1).is_none());
+        assert!(matches!(x.get(key2), Some(99)));
+    }
+    #[test]
+    fn check_iter_hops_over_vacant_runs() {
+        let mut x: HopSlotMap<i32> = HopSlotMap::new(8, 8).unwrap();
+        let keys: Vec<_> = (0..6).map(|i| x.add(i).unwrap()).collect();
+        // remove a run in the middle and one at the very end
+        x.remove(keys[2]);
+        x.remove(keys[3]);
+        x.remove(keys[5]);
+        let mut seen: Vec<i32> = x.iter().map(|(_, v)| *v).collect();
+        seen.sort();
+        assert_eq!(seen, vec![0, 1, 4]);
+        assert_eq!(x.len(), 3);
+    }
+    #[test]
+    fn check_merge_adjacent_runs_on_remove() {
+        let mut x: HopSlotMap<i32> = HopSlotMap::new(6, 6).unwrap();
+        let keys: Vec<_> = (0..6).map(|i| x.add(i).unwrap()).collect();
+        x.remove(keys[1]);
+        x.remove(keys[3]);
+        // removing index 2 should merge the two single-slot runs around it
+        // into one run spanning [1, 3]
+        x.remove(keys[2]);
+        let mut seen: Vec<i32> = x.iter().map(|(_, v)| *v).collect();
+        seen.sort();
+        assert_eq!(seen, vec![0, 4, 5]);
+        // the whole merged run should be reusable again
+        let a = x.add(10).unwrap();
+        let b = x.add(11).unwrap();
+        let c = x.add(12).unwrap();
+        assert!(matches!(x.get(a), Some(10)));
+        assert!(matches!(x.get(b), Some(11)));
+        assert!(matches!(x.get(c), Some(12)));
+        assert_eq!(x.len(), 6);
+    }
+    #[test]
+    fn check_iter_mut_updates_values() {
+        let mut x: HopSlotMap<i32> = HopSlotMap::new(4, 4).unwrap();
+        let k1 = x.add(1).unwrap();
+        let _k2 = x.add(2).unwrap();
+        x.remove(k1);
+        x.add(3).unwrap();
+        for v in x.values_mut() {
+            *v *= 10;
+        }
+        let mut values: Vec<i32> = x.values().copied().collect();
+        values.sort();
+        assert_eq!(values, vec![20, 30]);
+    }
+    #[test]
+    fn check_remove_retires_slot_instead_of_wrapping_version() {
+        let mut x: HopSlotMap<i32> = HopSlotMap::new(1, 2).unwrap();
+        x.add(1).unwrap();
+        // simulate this slot having already cycled up to its last valid
+        // version, rather than actually looping u32::MAX times
+        x.data[0].version = u32::MAX;
+        let key = Key::new(0, u32::MAX).unwrap();
+        assert_eq!(x.remove(key), Some(1));
+        assert_eq!(x.len(), 0);
+        // retired: a later add must not hand this index back out, and
+        // iteration must still hop over the retired slot correctly
+        let key2 = x.add(2).unwrap();
+        unsafe {
+            assert!(matches!(key2, Key { inner: crate::KeyInner { index: 1, .. } }));
+        }
+        assert_eq!(x.iter().map(|(_, v)| *v).collect::<Vec<_>>(), vec![2]);
+    }
+}
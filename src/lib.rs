@@ -1,11 +1,16 @@
 use std::marker::PhantomData;
+use std::mem::{ManuallyDrop, MaybeUninit};
 pub use error::Error;
+mod hop;
+pub use hop::HopSlotMap;
+mod secondary;
+pub use secondary::SecondaryMap;
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct KeyInner {
     index: u32,
-    generation: u32,
+    version: u32,
 }
 /// A 64-bit value that is unique to a value stored in a Slots data
 /// structure. Converts to/from a u64 using `From` trait implementations.
@@ -14,39 +19,48 @@ pub struct KeyInner {
 ///
 /// The index u32::MAX is reserved, leaving the maximum possible number of
 /// addressable slots equal to u32::MAX - 1.
-#[derive(Clone, Copy)]
 #[repr(C)]
 pub union Key<V> {
     x: u64,
     inner: KeyInner,
     _t: PhantomData<V>,
 }
+// manually implemented rather than derived: a derive would add a spurious
+// `V: Clone`/`V: Copy` bound, even though V is never actually stored (only
+// held via PhantomData), which would make `Key<V>` stop being Copy for any
+// non-Copy value type.
+impl<V> Clone for Key<V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<V> Copy for Key<V> {}
 impl<V> Key<V> {
-    fn new(index: usize, generation: u32) -> Result<Self, Error> {
+    fn new(index: usize, version: u32) -> Result<Self, Error> {
         if index as u32 == u32::MAX {
             return Err(Error::IndexOutOfBounds)
         }
         Ok(Self {
             inner: KeyInner {
                 index: index as u32,
-                generation,
+                version,
             },
         })
     }
     /// returns new key with index = u32::MAX
-    fn new_special(generation: u32) -> Result<Self, Error> {
+    fn new_special(version: u32) -> Result<Self, Error> {
         Ok(Self {
             inner: KeyInner {
                 index: u32::MAX,
-                generation,
+                version,
             },
         })
     }
     fn index(&self) -> usize {
         unsafe { self.inner.index as usize }
     }
-    fn generation(&self) -> u32 {
-        unsafe { self.inner.generation }
+    fn version(&self) -> u32 {
+        unsafe { self.inner.version }
     }
 }
 impl<V> PartialEq for Key<V> {
@@ -70,8 +84,8 @@ impl<V> std::fmt::Debug for Key<V> {
         f.write_str("Key <")?;
         unsafe {
             f.write_fmt(format_args!(
-                "gen: {}, index: {}",
-                self.inner.generation, self.inner.index
+                "ver: {}, index: {}",
+                self.inner.version, self.inner.index
             ))
         }?;
         f.write_str(">")
@@ -86,47 +100,54 @@ impl<V> std::fmt::Display for Key<V> {
 /// A key-value data structure that stores values in a Vec for O(1)
 /// retrievals and additions. Keys are weak and versioned: the value in the
 /// referenced slot may be dropped at any time, and subsequent retrievals
-/// with the same key will fail. Up to u32::MAX generations are supported.
-/// Up to (u32::MAX - 1) slots are supported. 
+/// with the same key will fail.
+///
+/// Each slot carries its own version counter instead of the map sharing one
+/// global counter, so the limit is on how often any single slot is reused
+/// rather than on the total number of insertions ever made across the whole
+/// map. A slot's version advances by two per reuse and stops at u32::MAX; a
+/// slot that reaches it is retired and never handed out again, while the
+/// other slots keep working. Up to (u32::MAX - 1) slots are supported.
 ///
 /// This thing is an essentially an allocator that hands out versioned
 /// indexes instead of pointers directly into memory.
+///
+/// Empty slots are tracked with an intrusive free list threaded through the
+/// slots themselves (each empty slot stores the index of the next empty
+/// slot), so there is no separate Vec of free indexes to allocate or keep in
+/// sync.
 pub struct SlotMap<V> {
     max_slots: usize,
-    generation: u32, // gen number last used to store a value
+    unique_counter: u32, // counter used only by get_unique_key; unrelated to slot versions
     data: Vec<Slot<V>>,
-    openlist: Vec<usize>, // list of empty slot indexes
+    free_head: u32, // index of the first empty slot, or u32::MAX if none
+    len: usize, // number of occupied slots
 }
 impl<V> SlotMap<V> {
     pub fn new(initial_slots: u32, max_slots: u32) -> Result<Self, Error> {
         if initial_slots > max_slots { return Err(Error::InvalidArgument) }
         if max_slots == u32::MAX { return Err(Error::InvalidArgument) }
         let data = Vec::with_capacity(initial_slots as usize);
-        let openlist = Vec::with_capacity(initial_slots as usize);
         Ok(Self {
             max_slots: max_slots as usize,
-            generation: 0, 
+            unique_counter: 0,
             data,
-            openlist,
+            free_head: u32::MAX,
+            len: 0,
         })
     }
-    // returns next generation
-    fn increment_generation(&mut self) -> Result<u32, Error> {
-        if let Some(gen) = self.generation.checked_add(1) {
-            self.generation = gen;
-            Ok(gen)
-        } else {
-            Err(Error::MaxGenerationReached)
-        }
-    }
     /// Returns the value stored in the slot, or None if the key is out of
     /// date or the slot is empty.
     pub fn remove(&mut self, key: Key<V>) -> Option<V> {
         let index = key.index();
+        let free_head = self.free_head;
         self.data.get_mut(index)
-            .and_then(|slot| slot.remove(key))
-            .map(|v| {
-                self.openlist.push(index);
+            .and_then(|slot| slot.remove(key, free_head))
+            .map(|(v, reusable)| {
+                if reusable {
+                    self.free_head = index as u32;
+                }
+                self.len -= 1;
                 v
             })
     }
@@ -144,15 +165,16 @@ impl<V> SlotMap<V> {
     }
     /// adds a new value, returns the key.
     pub fn add(&mut self, value: V) -> Result<Key<V>, Error> {
-        let generation = self.increment_generation()?;
-        if let Some(index) = self.openlist.pop() {
+        if self.free_head != u32::MAX {
             // reuse an existing empty slot
+            let index = self.free_head as usize;
             if let Some(slot) = self.data.get_mut(index) {
                 if slot.is_empty() {
-                    // store the value
-                    slot.value = value;
-                    slot.generation = generation;
-                    return Key::new(index, generation);
+                    let next_free = unsafe { slot.data.next_free };
+                    let version = slot.occupy(value)?;
+                    self.free_head = next_free;
+                    self.len += 1;
+                    return Key::new(index, version);
                 } else {
                     return Err(Error::SlotNotEmpty);
                 }
@@ -165,71 +187,361 @@ impl<V> SlotMap<V> {
             if index >= self.max_slots {
                 return Err(Error::NoFreeSlots);
             }
-            self.data.push(Slot::new(generation, value));
-            Key::new(index, generation)
+            self.data.push(Slot::new(value));
+            self.len += 1;
+            Key::new(index, 1)
         }
     }
-    /// returns a key that increments the generation, guaranteeing
-    /// uniqueness. The index part of the key is set to zero. If used with
-    /// .get(), it will always result in a None value returned.
+    /// returns a key that increments a counter private to this method,
+    /// guaranteeing uniqueness. The index part of the key is set to
+    /// u32::MAX. If used with .get(), it will always result in a None value
+    /// returned.
     pub fn get_unique_key(&mut self) -> Result<Key<V>, Error> {
-        let gen = self.increment_generation()?;
-        Key::new_special(gen)
+        let counter = self.unique_counter.checked_add(1).ok_or(Error::MaxVersionReached)?;
+        self.unique_counter = counter;
+        Key::new_special(counter)
     }
     /// returns number of occupied slots
     pub fn len(&self) -> usize {
-        self.data.len() - self.openlist.len()
+        self.len
+    }
+    /// returns true if there are no occupied slots
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// returns an iterator over `(Key<V>, &V)` pairs for every occupied
+    /// slot, in slot order. Vacant slots are skipped.
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter { slots: self.data.iter().enumerate() }
+    }
+    /// like [`SlotMap::iter`], but yields mutable references to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, V> {
+        IterMut { slots: self.data.iter_mut().enumerate() }
+    }
+    /// returns an iterator over the keys of every occupied slot.
+    pub fn keys(&self) -> Keys<'_, V> {
+        Keys { inner: self.iter() }
+    }
+    /// returns an iterator over references to every occupied value.
+    pub fn values(&self) -> Values<'_, V> {
+        Values { inner: self.iter() }
+    }
+    /// returns an iterator over mutable references to every occupied value.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, V> {
+        ValuesMut { inner: self.iter_mut() }
+    }
+    /// removes every occupied slot, yielding `(Key<V>, V)` pairs as they are
+    /// removed. Dropping the iterator before it is exhausted removes any
+    /// remaining entries without yielding them.
+    pub fn drain(&mut self) -> Drain<'_, V> {
+        Drain { map: self, index: 0 }
+    }
+    /// keeps only the entries for which `f` returns true, removing the rest
+    /// and returning their slots to the free list.
+    pub fn retain(&mut self, mut f: impl FnMut(Key<V>, &mut V) -> bool) {
+        for index in 0..self.data.len() {
+            let slot = &mut self.data[index];
+            if slot.is_empty() {
+                continue;
+            }
+            let key = Key::new(index, slot.version).expect("occupied slot has a valid index");
+            let keep = f(key, unsafe { &mut slot.data.value });
+            if !keep {
+                let next_free = self.free_head;
+                let (_, reusable) = unsafe { slot.unchecked_remove(next_free) };
+                if reusable {
+                    self.free_head = index as u32;
+                }
+                self.len -= 1;
+            }
+        }
+    }
+    /// Reserves a slot without requiring a constructed `V` up front,
+    /// returning its key and a writable, uninitialized cell for the caller
+    /// to initialize in place. The slot is held off the free list but does
+    /// not count as occupied until [`SlotMap::occupy`] is called with the
+    /// returned key; forgetting to call it leaks the slot.
+    pub fn reserve(&mut self) -> Result<(Key<V>, &mut MaybeUninit<V>), Error> {
+        let index = if self.free_head != u32::MAX {
+            let index = self.free_head as usize;
+            let next_free = unsafe { self.data[index].data.next_free };
+            self.free_head = next_free;
+            index
+        } else {
+            let index = self.data.len();
+            if index >= self.max_slots {
+                return Err(Error::NoFreeSlots);
+            }
+            self.data.push(Slot { version: 0, data: SlotData { next_free: u32::MAX } });
+            index
+        };
+        let version = self.data[index].version.checked_add(1).ok_or(Error::MaxVersionReached)?;
+        let key = Key::new(index, version)?;
+        // addr_of_mut! rather than `&mut self.data[index].data.value`: the
+        // slot holds no valid V yet (a next_free index or leftover bytes
+        // from a prior take), and merely forming a typed reference over
+        // that would already be UB for a V with a non-trivial validity
+        // invariant, before the caller ever gets a chance to initialize it.
+        let cell = unsafe {
+            &mut *(std::ptr::addr_of_mut!(self.data[index].data.value) as *mut MaybeUninit<V>)
+        };
+        Ok((key, cell))
+    }
+    /// Marks a slot reserved by [`SlotMap::reserve`] as live, using the
+    /// value the caller already wrote into the cell `reserve` handed back.
+    pub fn occupy(&mut self, key: Key<V>) -> Result<(), Error> {
+        let index = key.index();
+        let slot = self.data.get_mut(index).ok_or(Error::IndexOutOfBounds)?;
+        if !slot.is_empty() {
+            return Err(Error::SlotNotEmpty);
+        }
+        let version = slot.version.checked_add(1).ok_or(Error::MaxVersionReached)?;
+        if version != key.version() {
+            return Err(Error::SlotNotEmpty);
+        }
+        slot.version = version;
+        self.len += 1;
+        Ok(())
+    }
+    /// Removes the value at `key` without moving it out, returning a guard
+    /// that derefs to `&mut V` so the caller can drain or reset the
+    /// value's resources in place first, e.g. to reclaim a handle instead
+    /// of paying for a copy. The slot is only returned to the free list
+    /// (and whatever remains of the value dropped) once the guard itself
+    /// is dropped.
+    pub fn remove_in_place(&mut self, key: Key<V>) -> Option<Vacating<'_, V>> {
+        let index = key.index();
+        let slot = self.data.get(index)?;
+        if slot.is_empty() || slot.version != key.version() {
+            return None;
+        }
+        Some(Vacating { map: self, index })
+    }
+}
+impl<V> Drop for SlotMap<V> {
+    fn drop(&mut self) {
+        // Vacant slots hold no V at all, so only occupied slots need their
+        // value dropped; the union itself has no drop glue of its own.
+        for slot in self.data.iter_mut() {
+            if !slot.is_empty() {
+                unsafe { ManuallyDrop::drop(&mut slot.data.value) };
+            }
+        }
     }
 }
 
+union SlotData<V> {
+    value: ManuallyDrop<V>,
+    next_free: u32,
+}
 struct Slot<V> {
-    generation: u32, // 0 marks an empty slot
-    value: V
+    version: u32, // even = vacant, odd = occupied
+    data: SlotData<V>,
 }
 impl<V> Slot<V> {
-    fn new(generation: u32, value: V) -> Self {
-        Self { generation, value }
+    fn new(value: V) -> Self {
+        Self {
+            version: 1,
+            data: SlotData { value: ManuallyDrop::new(value) },
+        }
     }
     fn is_empty(&self) -> bool {
-        self.generation == 0
+        self.version % 2 == 0
     }
-    // checks the key generation
-    fn remove(&mut self, key: Key<V>) -> Option<V> {
-        if self.generation > 0 && key.generation() == self.generation {
-            let v = unsafe { self.unchecked_remove() };
-            Some(v)
+    // moves a vacant slot to occupied, storing value and bumping the
+    // version by one (odd); returns the new version
+    fn occupy(&mut self, value: V) -> Result<u32, Error> {
+        let version = self.version.checked_add(1).ok_or(Error::MaxVersionReached)?;
+        self.version = version;
+        self.data = SlotData { value: ManuallyDrop::new(value) };
+        Ok(version)
+    }
+    // checks the key version
+    fn remove(&mut self, key: Key<V>, next_free: u32) -> Option<(V, bool)> {
+        if !self.is_empty() && key.version() == self.version {
+            Some(unsafe { self.unchecked_remove(next_free) })
         } else {
             None
         }
     }
-    unsafe fn unchecked_remove(&mut self) -> V {
-        self.generation = 0;
-        let swap_value: V = std::mem::zeroed();
-        std::mem::replace(&mut self.value, swap_value)
+    // removes the value and bumps the version, returning it along with
+    // whether the slot may be returned to the free list. Once a slot's
+    // version is at u32::MAX, bumping it would wrap back around to an
+    // already-issued version, letting a long-stale Key for this slot start
+    // matching again — exactly the ABA hazard per-slot versioning exists to
+    // prevent. Instead the slot is permanently retired: its version is left
+    // wrapped (so it still reads as vacant) but it is never linked back into
+    // the free list, so `add`/`reserve` can never hand its index out again.
+    unsafe fn unchecked_remove(&mut self, next_free: u32) -> (V, bool) {
+        let reusable = self.version != u32::MAX;
+        self.version = self.version.wrapping_add(1);
+        // Take the value out without ever materializing a bogus V: a
+        // vacant slot holds no value at all, just the next_free index.
+        let value = unsafe { ManuallyDrop::take(&mut self.data.value) };
+        self.data.next_free = if reusable { next_free } else { u32::MAX };
+        (value, reusable)
     }
-    // checks the generation against the key generation
+    // checks the key version
     fn get(&self, key: Key<V>) -> Option<&V> {
-        if self.generation > 0 && key.generation() == self.generation {
-            Some(&self.value)
+        if !self.is_empty() && key.version() == self.version {
+            Some(unsafe { &self.data.value })
         } else {
             None
         }
     }
-    // checks the generation against the key generation
+    // checks the key version
     fn get_mut(&mut self, key: Key<V>) -> Option<&mut V> {
-        if self.generation > 0 && key.generation() == self.generation {
-            Some(&mut self.value)
+        if !self.is_empty() && key.version() == self.version {
+            Some(unsafe { &mut self.data.value })
         } else {
             None
         }
     }
 }
 
+/// Iterator over `(Key<V>, &V)` pairs, returned by [`SlotMap::iter`].
+pub struct Iter<'a, V> {
+    slots: std::iter::Enumerate<std::slice::Iter<'a, Slot<V>>>,
+}
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (Key<V>, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        for (index, slot) in self.slots.by_ref() {
+            if !slot.is_empty() {
+                let key = Key::new(index, slot.version).expect("occupied slot has a valid index");
+                return Some((key, unsafe { &slot.data.value }));
+            }
+        }
+        None
+    }
+}
+impl<'a, V> std::iter::FusedIterator for Iter<'a, V> {}
+
+/// Iterator over `(Key<V>, &mut V)` pairs, returned by [`SlotMap::iter_mut`].
+pub struct IterMut<'a, V> {
+    slots: std::iter::Enumerate<std::slice::IterMut<'a, Slot<V>>>,
+}
+impl<'a, V> Iterator for IterMut<'a, V> {
+    type Item = (Key<V>, &'a mut V);
+    fn next(&mut self) -> Option<Self::Item> {
+        for (index, slot) in self.slots.by_ref() {
+            if !slot.is_empty() {
+                let key = Key::new(index, slot.version).expect("occupied slot has a valid index");
+                return Some((key, unsafe { &mut slot.data.value }));
+            }
+        }
+        None
+    }
+}
+impl<'a, V> std::iter::FusedIterator for IterMut<'a, V> {}
+
+/// Iterator over keys, returned by [`SlotMap::keys`].
+pub struct Keys<'a, V> {
+    inner: Iter<'a, V>,
+}
+impl<'a, V> Iterator for Keys<'a, V> {
+    type Item = Key<V>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
+impl<'a, V> std::iter::FusedIterator for Keys<'a, V> {}
+
+/// Iterator over value references, returned by [`SlotMap::values`].
+pub struct Values<'a, V> {
+    inner: Iter<'a, V>,
+}
+impl<'a, V> Iterator for Values<'a, V> {
+    type Item = &'a V;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+}
+impl<'a, V> std::iter::FusedIterator for Values<'a, V> {}
+
+/// Iterator over mutable value references, returned by [`SlotMap::values_mut`].
+pub struct ValuesMut<'a, V> {
+    inner: IterMut<'a, V>,
+}
+impl<'a, V> Iterator for ValuesMut<'a, V> {
+    type Item = &'a mut V;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+}
+impl<'a, V> std::iter::FusedIterator for ValuesMut<'a, V> {}
+
+/// Draining iterator over `(Key<V>, V)` pairs, returned by [`SlotMap::drain`].
+/// Every occupied slot is removed and returned to the free list as the
+/// iterator advances.
+pub struct Drain<'a, V> {
+    map: &'a mut SlotMap<V>,
+    index: usize,
+}
+impl<'a, V> Iterator for Drain<'a, V> {
+    type Item = (Key<V>, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.map.data.len() {
+            let index = self.index;
+            self.index += 1;
+            let slot = &mut self.map.data[index];
+            if !slot.is_empty() {
+                let version = slot.version;
+                let next_free = self.map.free_head;
+                let (value, reusable) = unsafe { slot.unchecked_remove(next_free) };
+                if reusable {
+                    self.map.free_head = index as u32;
+                }
+                self.map.len -= 1;
+                let key = Key::new(index, version).expect("occupied slot has a valid index");
+                return Some((key, value));
+            }
+        }
+        None
+    }
+}
+impl<'a, V> std::iter::FusedIterator for Drain<'a, V> {}
+impl<'a, V> Drop for Drain<'a, V> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+/// Guard returned by [`SlotMap::remove_in_place`]. Derefs to the value
+/// still sitting in its slot; dropping the guard finishes the removal,
+/// returning the slot to the free list and dropping whatever is left of
+/// the value at that point.
+pub struct Vacating<'a, V> {
+    map: &'a mut SlotMap<V>,
+    index: usize,
+}
+impl<'a, V> std::ops::Deref for Vacating<'a, V> {
+    type Target = V;
+    fn deref(&self) -> &V {
+        unsafe { &self.map.data[self.index].data.value }
+    }
+}
+impl<'a, V> std::ops::DerefMut for Vacating<'a, V> {
+    fn deref_mut(&mut self) -> &mut V {
+        unsafe { &mut self.map.data[self.index].data.value }
+    }
+}
+impl<'a, V> Drop for Vacating<'a, V> {
+    fn drop(&mut self) {
+        let next_free = self.map.free_head;
+        let (_, reusable) = unsafe { self.map.data[self.index].unchecked_remove(next_free) };
+        if reusable {
+            self.map.free_head = self.index as u32;
+        }
+        self.map.len -= 1;
+    }
+}
+
 mod error {
     #[derive(Debug, Clone, Copy)]
     pub enum Error {
         IndexOutOfBounds,
-        MaxGenerationReached,
+        MaxVersionReached,
         SlotNotEmpty,
         NoFreeSlots,
         InvalidArgument,
@@ -249,36 +561,36 @@ mod checks {
 
     #[test]
     fn new_slotmap() {
-        let x: Result<SlotMap<i32>, Error> = SlotMap::new(4, 10); 
+        let x: Result<SlotMap<i32>, Error> = SlotMap::new(4, 10);
         assert!(x.is_ok());
-        let x: Result<SlotMap<i32>, Error> = SlotMap::new(40, 10); 
+        let x: Result<SlotMap<i32>, Error> = SlotMap::new(40, 10);
         assert!(matches!(x, Err(Error::InvalidArgument)));
-        let x: Result<SlotMap<i32>, Error> = SlotMap::new(10, 10); 
+        let x: Result<SlotMap<i32>, Error> = SlotMap::new(10, 10);
         assert!(x.is_ok());
-        let x: Result<SlotMap<i32>, Error> = SlotMap::new(10, u32::MAX); 
+        let x: Result<SlotMap<i32>, Error> = SlotMap::new(10, u32::MAX);
         assert!(matches!(x, Err(Error::InvalidArgument)));
     }
     #[test]
-    fn check_slotmap_increment_gen() {
+    fn check_unique_key_counter() {
         let mut x: SlotMap<i32> = SlotMap::new(3, 5).unwrap();
-        assert_eq!(x.generation, 0);
-        let y = x.increment_generation();
-        assert_eq!(x.generation, 1);
-        assert!(matches!(y, Ok(1)));
-        x.generation = u32::MAX;
-        let y = x.increment_generation();
-        assert!(matches!(y, Err(Error::MaxGenerationReached)));
+        let k1 = x.get_unique_key().unwrap();
+        let k2 = x.get_unique_key().unwrap();
+        assert!(k1 != k2);
+        assert!(x.get(k1).is_none());
+        x.unique_counter = u32::MAX;
+        let k3 = x.get_unique_key();
+        assert!(matches!(k3, Err(Error::MaxVersionReached)));
     }
     #[test]
     fn check_slotmap_add_expand() {
         let mut x: SlotMap<i32> = SlotMap::new(2, 4).unwrap();
         let key = x.add(3);
         unsafe {
-            assert!(matches!(key, Ok(Key { inner: KeyInner { index: 0, generation: 1}})));
+            assert!(matches!(key, Ok(Key { inner: KeyInner { index: 0, version: 1}})));
         }
         let key = x.add(9);
         unsafe {
-            assert!(matches!(key, Ok(Key { inner: KeyInner { index: 1, generation: 2}})));
+            assert!(matches!(key, Ok(Key { inner: KeyInner { index: 1, version: 1}})));
         }
         let key = x.add(1);
         assert!(matches!(key, Ok(_)));
@@ -297,7 +609,7 @@ mod checks {
         unsafe {
             assert!(matches!(key3, Ok(Key { inner: KeyInner {index: 0, ..}})));
         }
-    
+
     }
     #[test]
     fn check_slotmap_remove() {
@@ -317,4 +629,145 @@ mod checks {
         assert!(matches!(x.get(key1), Some(3)));
         assert!(matches!(x.get(key2), Some(5)));
     }
+    #[test]
+    fn check_slotmap_free_list_chain() {
+        let mut x: SlotMap<i32> = SlotMap::new(4, 4).unwrap();
+        let key1 = x.add(1).unwrap();
+        let key2 = x.add(2).unwrap();
+        let key3 = x.add(3).unwrap();
+        x.remove(key1);
+        x.remove(key2);
+        // free list should hand back the most recently freed slot first
+        let key4 = x.add(4).unwrap();
+        unsafe {
+            assert!(matches!(key4, Key { inner: KeyInner { index: 1, .. } }));
+        }
+        let key5 = x.add(5).unwrap();
+        unsafe {
+            assert!(matches!(key5, Key { inner: KeyInner { index: 0, .. } }));
+        }
+        assert_eq!(x.len(), 3);
+        assert!(matches!(x.get(key3), Some(3)));
+    }
+    #[test]
+    fn check_drop_runs_for_remaining_and_removed_values() {
+        use std::rc::Rc;
+        use std::cell::Cell;
+        let drops = Rc::new(Cell::new(0));
+        struct Counted(Rc<Cell<i32>>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+        let mut x: SlotMap<Counted> = SlotMap::new(2, 4).unwrap();
+        let key1 = x.add(Counted(drops.clone())).unwrap();
+        let _key2 = x.add(Counted(drops.clone())).unwrap();
+        x.remove(key1);
+        assert_eq!(drops.get(), 1);
+        drop(x);
+        assert_eq!(drops.get(), 2);
+    }
+    #[test]
+    fn check_slot_version_toggle_rejects_stale_key() {
+        let mut x: SlotMap<i32> = SlotMap::new(2, 2).unwrap();
+        let key1 = x.add(1).unwrap();
+        x.remove(key1);
+        let key2 = x.add(2).unwrap();
+        // key1 reused the same index but is now a stale version
+        assert!(x.get(key1).is_none());
+        assert!(matches!(x.get(key2), Some(2)));
+    }
+    #[test]
+    fn check_iter_skips_vacant_slots() {
+        let mut x: SlotMap<i32> = SlotMap::new(4, 4).unwrap();
+        let key1 = x.add(1).unwrap();
+        let _key2 = x.add(2).unwrap();
+        let key3 = x.add(3).unwrap();
+        x.remove(key1);
+        let mut seen: Vec<i32> = x.iter().map(|(_, v)| *v).collect();
+        seen.sort();
+        assert_eq!(seen, vec![2, 3]);
+        assert_eq!(x.keys().count(), 2);
+        for v in x.values_mut() {
+            *v *= 10;
+        }
+        let mut values: Vec<i32> = x.values().copied().collect();
+        values.sort();
+        assert_eq!(values, vec![20, 30]);
+        assert!(matches!(x.get(key3), Some(30)));
+    }
+    #[test]
+    fn check_drain_empties_map() {
+        let mut x: SlotMap<i32> = SlotMap::new(4, 4).unwrap();
+        x.add(1).unwrap();
+        x.add(2).unwrap();
+        x.add(3).unwrap();
+        let mut drained: Vec<i32> = x.drain().map(|(_, v)| v).collect();
+        drained.sort();
+        assert_eq!(drained, vec![1, 2, 3]);
+        assert_eq!(x.len(), 0);
+        assert!(x.is_empty());
+        // slots are back on the free list and can be reused
+        let key = x.add(9).unwrap();
+        assert!(matches!(x.get(key), Some(9)));
+    }
+    #[test]
+    fn check_retain_removes_entries_and_frees_slots() {
+        let mut x: SlotMap<i32> = SlotMap::new(4, 4).unwrap();
+        x.add(1).unwrap();
+        x.add(2).unwrap();
+        x.add(3).unwrap();
+        x.retain(|_, v| *v % 2 == 1);
+        let mut remaining: Vec<i32> = x.values().copied().collect();
+        remaining.sort();
+        assert_eq!(remaining, vec![1, 3]);
+        assert_eq!(x.len(), 2);
+        // the freed slot can be reused
+        let key = x.add(4).unwrap();
+        assert!(matches!(x.get(key), Some(4)));
+    }
+    #[test]
+    fn check_reserve_then_occupy() {
+        let mut x: SlotMap<String> = SlotMap::new(2, 4).unwrap();
+        let (key, cell) = x.reserve().unwrap();
+        cell.write(String::from("hello"));
+        // not yet visible: occupy hasn't been called
+        assert!(x.get(key).is_none());
+        assert_eq!(x.len(), 0);
+        x.occupy(key).unwrap();
+        assert_eq!(x.get(key), Some(&String::from("hello")));
+        assert_eq!(x.len(), 1);
+    }
+    #[test]
+    fn check_remove_in_place_defers_slot_return() {
+        let mut x: SlotMap<Vec<i32>> = SlotMap::new(2, 4).unwrap();
+        let key1 = x.add(vec![1, 2, 3]).unwrap();
+        {
+            let mut vacating = x.remove_in_place(key1).unwrap();
+            // salvage data out of the value before it is dropped
+            assert_eq!(vacating.pop(), Some(3));
+        }
+        assert!(x.get(key1).is_none());
+        assert_eq!(x.len(), 0);
+        // the slot was returned to the free list once the guard dropped
+        let key2 = x.add(vec![9]).unwrap();
+        assert!(matches!(x.get(key2), Some(v) if v == &vec![9]));
+    }
+    #[test]
+    fn check_remove_retires_slot_instead_of_wrapping_version() {
+        let mut x: SlotMap<i32> = SlotMap::new(1, 2).unwrap();
+        x.add(1).unwrap();
+        // simulate this slot having already cycled up to its last valid
+        // version, rather than actually looping u32::MAX times
+        x.data[0].version = u32::MAX;
+        let key = Key::new(0, u32::MAX).unwrap();
+        assert_eq!(x.remove(key), Some(1));
+        assert_eq!(x.len(), 0);
+        // retired: a later add must not hand this index back out
+        let key2 = x.add(2).unwrap();
+        unsafe {
+            assert!(matches!(key2, Key { inner: KeyInner { index: 1, .. } }));
+        }
+    }
 }
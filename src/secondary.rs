@@ -0,0 +1,127 @@
+use crate::Key;
+use std::marker::PhantomData;
+
+enum Slot<T> {
+    Vacant,
+    Occupied { version: u32, value: T },
+}
+
+/// A map that attaches extra data to keys handed out by a [`crate::SlotMap`]
+/// (or [`crate::HopSlotMap`]), without needing a reference to that map.
+///
+/// `SecondaryMap` stores its own `Vec` of version-tagged slots keyed by
+/// `key.index()`. A lookup also checks `key.version()` against the version
+/// it was inserted with, so a key for the same index with a different
+/// version (such as the key of a newer entry in the primary map) does not
+/// see it. The map is not notified when the primary map removes or
+/// replaces an entry: the key an entry was inserted with keeps finding it
+/// until it is removed here or overwritten by an insert for that index.
+/// Vacant slots carry no version at all, since there is nothing to compare
+/// against until something is inserted.
+pub struct SecondaryMap<V, T> {
+    data: Vec<Slot<T>>,
+    _t: PhantomData<V>,
+}
+impl<V, T> SecondaryMap<V, T> {
+    pub fn new() -> Self {
+        Self { data: Vec::new(), _t: PhantomData }
+    }
+    /// associates `value` with `key`, returning the value previously
+    /// associated with `key` if its version matched.
+    pub fn insert(&mut self, key: Key<V>, value: T) -> Option<T> {
+        let index = key.index();
+        if index == u32::MAX as usize {
+            // u32::MAX is the reserved index get_unique_key() hands out; it
+            // never names a real slot, so treat it as permanently vacant
+            // instead of growing the backing Vec to u32::MAX entries.
+            return None;
+        }
+        if index >= self.data.len() {
+            self.data.resize_with(index + 1, || Slot::Vacant);
+        }
+        let previous = std::mem::replace(&mut self.data[index], Slot::Vacant);
+        self.data[index] = Slot::Occupied { version: key.version(), value };
+        match previous {
+            Slot::Occupied { version, value } if version == key.version() => Some(value),
+            _ => None,
+        }
+    }
+    /// returns a reference to the value associated with `key`, or None if
+    /// nothing is stored for it or the stored version is stale.
+    pub fn get(&self, key: Key<V>) -> Option<&T> {
+        match self.data.get(key.index()) {
+            Some(Slot::Occupied { version, value }) if *version == key.version() => Some(value),
+            _ => None,
+        }
+    }
+    /// returns a mutable reference to the value associated with `key`, or
+    /// None if nothing is stored for it or the stored version is stale.
+    pub fn get_mut(&mut self, key: Key<V>) -> Option<&mut T> {
+        match self.data.get_mut(key.index()) {
+            Some(Slot::Occupied { version, value }) if *version == key.version() => Some(value),
+            _ => None,
+        }
+    }
+    /// removes and returns the value associated with `key`, if its version
+    /// matched.
+    pub fn remove(&mut self, key: Key<V>) -> Option<T> {
+        let slot = self.data.get_mut(key.index())?;
+        match slot {
+            Slot::Occupied { version, .. } if *version == key.version() => {
+                match std::mem::replace(slot, Slot::Vacant) {
+                    Slot::Occupied { value, .. } => Some(value),
+                    Slot::Vacant => unreachable!(),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+impl<V, T> Default for SecondaryMap<V, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod checks {
+    use super::*;
+    use crate::SlotMap;
+
+    #[test]
+    fn check_insert_get_remove() {
+        let mut primary: SlotMap<&str> = SlotMap::new(2, 4).unwrap();
+        let key1 = primary.add("a").unwrap();
+        let key2 = primary.add("b").unwrap();
+        let mut secondary: SecondaryMap<&str, i32> = SecondaryMap::new();
+        assert_eq!(secondary.insert(key1, 1), None);
+        assert_eq!(secondary.insert(key2, 2), None);
+        assert_eq!(secondary.get(key1), Some(&1));
+        assert_eq!(secondary.remove(key1), Some(1));
+        assert_eq!(secondary.get(key1), None);
+        assert_eq!(secondary.get(key2), Some(&2));
+    }
+    #[test]
+    fn check_stale_key_after_primary_replaces_entry() {
+        let mut primary: SlotMap<&str> = SlotMap::new(2, 4).unwrap();
+        let key1 = primary.add("a").unwrap();
+        let mut secondary: SecondaryMap<&str, i32> = SecondaryMap::new();
+        secondary.insert(key1, 42);
+        primary.remove(key1);
+        let key2 = primary.add("c").unwrap();
+        // key2 reused key1's index but has a different version
+        assert_eq!(secondary.get(key1), Some(&42));
+        assert_eq!(secondary.get(key2), None);
+        secondary.insert(key2, 7);
+        assert_eq!(secondary.get(key1), None);
+        assert_eq!(secondary.get(key2), Some(&7));
+    }
+    #[test]
+    fn check_insert_rejects_unique_key_index_without_growing() {
+        let mut primary: SlotMap<&str> = SlotMap::new(2, 4).unwrap();
+        let unique_key = primary.get_unique_key().unwrap();
+        let mut secondary: SecondaryMap<&str, i32> = SecondaryMap::new();
+        assert_eq!(secondary.insert(unique_key, 1), None);
+        assert_eq!(secondary.get(unique_key), None);
+    }
+}